@@ -0,0 +1,116 @@
+// HMAC-SHA256 request authentication. Runner routes (claim/heartbeat/report)
+// carry no per-caller identity to protect, so they're gated by one shared
+// `RUNNER_AUTH_SECRET`. User-facing routes (fine-tune creation) authenticate
+// a specific user, so they're gated by a per-user API key looked up in D1:
+// the signature is computed with that key's own secret, not a secret shared
+// across every user, so holding one key can never be used to sign a request
+// asserting a *different* user's identity.
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::sync::OnceLock;
+use worker::*;
+
+type HmacSha256 = Hmac<Sha256>;
+
+static RUNNER_AUTH_SECRET: OnceLock<String> = OnceLock::new();
+
+/// Identity recovered from a verified API key, not from anything the client
+/// asserts.
+pub struct Claims {
+    pub user_id: String,
+}
+
+/// Loads the runner protocol's shared secret from the `RUNNER_AUTH_SECRET`
+/// binding, caching it for the lifetime of the isolate.
+pub fn runner_secret(env: &Env) -> Result<String> {
+    if let Some(secret) = RUNNER_AUTH_SECRET.get() {
+        return Ok(secret.clone());
+    }
+
+    let secret = env.secret("RUNNER_AUTH_SECRET")?.to_string();
+    let _ = RUNNER_AUTH_SECRET.set(secret.clone());
+    Ok(secret)
+}
+
+/// Verifies a runner request's `Authorization: <hex_signature>` header
+/// against the canonicalized request (method + path + body) and the shared
+/// runner secret. Runners have no per-caller identity to recover, so unlike
+/// `verify_signature` this only answers yes/no.
+pub async fn verify_runner_signature(req: &mut Request, secret: &str) -> Result<()> {
+    let signature_hex = req
+        .headers()
+        .get("Authorization")?
+        .ok_or_else(|| Error::from("Missing Authorization header"))?;
+
+    let canonical = canonical_request(req).await?;
+    let expected = sign(secret, &canonical)?;
+
+    if !constant_time_eq(expected.as_bytes(), signature_hex.as_bytes()) {
+        return Err(Error::from("Invalid signature"));
+    }
+
+    Ok(())
+}
+
+/// Verifies a user request's `Authorization: <key_id>:<hex_signature>`
+/// header by looking up `key_id`'s own secret in D1 and recomputing the
+/// signature with it, then returns the `user_id` that key belongs to. The
+/// client never asserts its own identity - `key_id` only selects which
+/// secret to check against, so a forged signature for someone else's
+/// `key_id` requires that user's secret, not just any valid one.
+pub async fn verify_signature(req: &mut Request, db: &D1Database) -> Result<Claims> {
+    let header = req
+        .headers()
+        .get("Authorization")?
+        .ok_or_else(|| Error::from("Missing Authorization header"))?;
+
+    let (key_id, signature_hex) = header
+        .split_once(':')
+        .ok_or_else(|| Error::from("Malformed Authorization header"))?;
+
+    let key = db
+        .prepare("SELECT user_id, secret FROM api_keys WHERE key_id = ? AND revoked_at IS NULL")
+        .bind(&[key_id.into()])?
+        .first::<ApiKeyRow>(None)
+        .await?
+        .ok_or_else(|| Error::from("Unknown or revoked API key"))?;
+
+    let canonical = canonical_request(req).await?;
+    let expected = sign(&key.secret, &canonical)?;
+
+    if !constant_time_eq(expected.as_bytes(), signature_hex.as_bytes()) {
+        return Err(Error::from("Invalid signature"));
+    }
+
+    Ok(Claims { user_id: key.user_id })
+}
+
+#[derive(Deserialize)]
+struct ApiKeyRow {
+    user_id: String,
+    secret: String,
+}
+
+async fn canonical_request(req: &mut Request) -> Result<String> {
+    let method = req.method().to_string();
+    let path = req.path();
+    let body = req.clone()?.text().await?;
+    Ok(format!("{}\n{}\n{}", method, path, body))
+}
+
+fn sign(secret: &str, message: &str) -> Result<String> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| Error::from(format!("Invalid auth secret: {}", e)))?;
+    mac.update(message.as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
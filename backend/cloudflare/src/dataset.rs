@@ -0,0 +1,208 @@
+// Dataset validation: goes beyond "does metadata.json exist" to actually
+// parse the dataset, check each record has the fields the chosen base model
+// expects, and enforce size limits — so malformed datasets are rejected at
+// submission time instead of failing deep inside training. The `format`
+// declared in metadata.json is read and reported, but not checked against
+// the records.
+
+use std::fmt;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// A dataset must have at least this many examples to be worth training on.
+const MIN_EXAMPLES: usize = 10;
+
+/// Hard ceiling on dataset size, overridable via the `MAX_DATASET_BYTES` var.
+const DEFAULT_MAX_DATASET_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Reads the `MAX_DATASET_BYTES` var, falling back to the default ceiling if
+/// it's unset or not a valid number.
+fn max_dataset_bytes(env: &Env) -> u64 {
+    env.var("MAX_DATASET_BYTES")
+        .ok()
+        .and_then(|v| v.to_string().parse().ok())
+        .unwrap_or(DEFAULT_MAX_DATASET_BYTES)
+}
+
+#[derive(Debug)]
+pub enum ValidationError {
+    NotFound,
+    InvalidFormat(String),
+    EmptyDataset,
+    MissingFields(String),
+    TooLarge { limit_bytes: u64, actual_bytes: u64 },
+    Io(String),
+}
+
+impl ValidationError {
+    /// HTTP status this error should surface as from `handle_fine_tune_request`.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ValidationError::NotFound => 404,
+            _ => 422,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::NotFound => write!(f, "Dataset not found"),
+            ValidationError::InvalidFormat(reason) => write!(f, "Invalid dataset format: {}", reason),
+            ValidationError::EmptyDataset => write!(f, "Dataset has no examples"),
+            ValidationError::MissingFields(reason) => write!(f, "Dataset records missing required fields: {}", reason),
+            ValidationError::TooLarge { limit_bytes, actual_bytes } => write!(
+                f,
+                "Dataset is too large: {} bytes exceeds the {} byte limit",
+                actual_bytes, limit_bytes
+            ),
+            ValidationError::Io(reason) => write!(f, "Failed to read dataset: {}", reason),
+        }
+    }
+}
+
+impl From<Error> for ValidationError {
+    fn from(e: Error) -> Self {
+        ValidationError::Io(e.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DatasetMetadata {
+    format: String,
+}
+
+/// Summary of a validated dataset, persisted alongside the dataset so
+/// training params (like `batch_size`) can be sanity-checked against it
+/// before the job is queued.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetReport {
+    pub dataset_id: String,
+    pub format: String,
+    pub example_count: usize,
+    pub byte_size: u64,
+}
+
+pub async fn validate_dataset(
+    env: &Env,
+    bucket: &R2Bucket,
+    dataset_id: &str,
+    base_model: &str,
+) -> std::result::Result<DatasetReport, ValidationError> {
+    let metadata_path = format!("datasets/{}/metadata.json", dataset_id);
+    let metadata_object = bucket
+        .get(&metadata_path)
+        .await?
+        .ok_or(ValidationError::NotFound)?;
+
+    let metadata_text = metadata_object
+        .body()
+        .ok_or_else(|| ValidationError::InvalidFormat("metadata.json has no body".to_string()))?
+        .text()
+        .await?;
+
+    let metadata: DatasetMetadata = serde_json::from_str(&metadata_text)
+        .map_err(|e| ValidationError::InvalidFormat(format!("could not parse metadata.json: {}", e)))?;
+
+    let data_path = format!("datasets/{}/data.jsonl", dataset_id);
+    let data_object = bucket
+        .get(&data_path)
+        .await?
+        .ok_or(ValidationError::NotFound)?;
+
+    let byte_size = data_object.size() as u64;
+    let max_bytes = max_dataset_bytes(env);
+    if byte_size > max_bytes {
+        return Err(ValidationError::TooLarge {
+            limit_bytes: max_bytes,
+            actual_bytes: byte_size,
+        });
+    }
+
+    let data_text = data_object
+        .body()
+        .ok_or_else(|| ValidationError::InvalidFormat("data.jsonl has no body".to_string()))?
+        .text()
+        .await?;
+
+    let required_fields = required_fields_for(base_model);
+    let mut example_count = 0usize;
+
+    for (line_no, line) in data_text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: serde_json::Value = serde_json::from_str(line).map_err(|e| {
+            ValidationError::InvalidFormat(format!("line {} is not valid JSON: {}", line_no + 1, e))
+        })?;
+
+        let missing: Vec<&str> = required_fields
+            .iter()
+            .filter(|field| record.get(**field).is_none())
+            .copied()
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(ValidationError::MissingFields(format!(
+                "line {} is missing {:?} (required for base model {})",
+                line_no + 1,
+                missing,
+                base_model
+            )));
+        }
+
+        example_count += 1;
+    }
+
+    if example_count == 0 {
+        return Err(ValidationError::EmptyDataset);
+    }
+
+    if example_count < MIN_EXAMPLES {
+        return Err(ValidationError::InvalidFormat(format!(
+            "only {} examples found, minimum is {}",
+            example_count, MIN_EXAMPLES
+        )));
+    }
+
+    Ok(DatasetReport {
+        dataset_id: dataset_id.to_string(),
+        format: metadata.format,
+        example_count,
+        byte_size,
+    })
+}
+
+/// Required JSONL record fields per base model family. Chat-tuned models
+/// expect a `messages` array; plain instruction models expect a
+/// prompt/completion pair.
+fn required_fields_for(base_model: &str) -> Vec<&'static str> {
+    if base_model.to_lowercase().contains("chat") {
+        vec!["messages"]
+    } else {
+        vec!["prompt", "completion"]
+    }
+}
+
+/// Persists the report so later steps (queueing, runner claim) can sanity
+/// check training params against dataset size without re-reading R2.
+pub async fn persist_report(db: &D1Database, report: &DatasetReport) -> Result<()> {
+    db.prepare(
+        "INSERT INTO dataset_reports (dataset_id, format, example_count, byte_size, created_at) \
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&[
+        report.dataset_id.clone().into(),
+        report.format.clone().into(),
+        (report.example_count as f64).into(),
+        (report.byte_size as f64).into(),
+        Utc::now().to_rfc3339().into(),
+    ])?
+    .run()
+    .await?;
+
+    Ok(())
+}
@@ -1,9 +1,11 @@
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use worker::*;
-use chrono::Utc;
+
+use crate::runner;
+use crate::state::{self, JobState};
 
 // Define the job message structure that matches what is sent to the queue
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct JobMessage {
     job_id: String,
     model_id: String,
@@ -12,7 +14,7 @@ struct JobMessage {
     training_params: TrainingParams,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct TrainingParams {
     epochs: u32,
     batch_size: u32,
@@ -25,7 +27,7 @@ struct TrainingParams {
 #[event(queue)]
 pub async fn queue(batch: MessageBatch<String>, env: Env, ctx: Context) -> Result<()> {
     console_log!("Processing {} messages from queue: {}", batch.messages.len(), batch.queue);
-    
+
     // Get database binding
     let db = match env.d1("DB") {
         Ok(db) => db,
@@ -34,8 +36,8 @@ pub async fn queue(batch: MessageBatch<String>, env: Env, ctx: Context) -> Resul
             return Err(Error::from(e));
         }
     };
-    
-    // Get R2 bucket binding
+
+    // Get R2 bucket binding (used to persist dead-letter error artifacts)
     let bucket = match env.r2("STORAGE") {
         Ok(bucket) => bucket,
         Err(e) => {
@@ -43,107 +45,104 @@ pub async fn queue(batch: MessageBatch<String>, env: Env, ctx: Context) -> Resul
             return Err(Error::from(e));
         }
     };
-    
+
+    // Re-queue any jobs whose runner went quiet before we hand out more work.
+    if let Err(e) = runner::sweep_expired_leases(&db).await {
+        console_error!("Failed to sweep expired leases: {}", e);
+    }
+
     // Process each message in the batch
     for msg in batch.messages.iter() {
-        match process_job_message(msg, &db, &bucket).await {
-            Ok(_) => {
-                console_log!("Successfully processed job {}", msg.id);
-            },
+        let job_message = match serde_json::from_str::<JobMessage>(&msg.body) {
+            Ok(data) => data,
             Err(e) => {
-                console_error!("Error processing job {}: {}", msg.id, e);
-                // Mark message for retry if it failed
-                msg.retry().map_err(|e| {
+                console_error!("Failed to parse job message {}: {}", msg.id, e);
+                if let Err(e) = msg.retry() {
                     console_error!("Failed to mark message for retry: {}", e);
-                    Error::from(e)
-                })?;
+                }
+                continue;
+            }
+        };
+
+        if let Err(e) = process_job_message(&job_message, &db, &env).await {
+            console_error!("Error processing job {}: {}", job_message.job_id, e);
+            if let Err(e) = handle_job_failure(&db, &bucket, &env, &job_message, &e).await {
+                console_error!("Failed to record failure for job {}: {}", job_message.job_id, e);
             }
+        } else {
+            console_log!("Successfully processed job {}", job_message.job_id);
         }
     }
-    
+
     Ok(())
 }
 
-async fn process_job_message(msg: &QueueMessage<String>, db: &D1Database, bucket: &R2Bucket) -> Result<()> {
-    // Parse the job message
-    let job_message = match serde_json::from_str::<JobMessage>(&msg.body) {
-        Ok(data) => data,
-        Err(e) => {
-            return Err(Error::from(format!("Failed to parse job message: {}", e)));
-        }
-    };
-    
-    // Update job status to "processing"
-    update_job_status(db, &job_message.job_id, "processing").await?;
-    
-    // Simulate job processing (this would normally be more complex)
-    // In a real implementation, you might:
-    // 1. Download the dataset from R2
-    // 2. Submit the job to a training backend (e.g., AWS Batch, GCP Vertex AI)
-    // 3. Poll for completion or set up a webhook
-    
-    // For this example, we'll simulate successful processing
-    console_log!("Processing fine-tuning job {} for model {}", job_message.job_id, job_message.model_id);
-    
-    // Update model status to "trained" after job completes
-    update_model_status(db, &job_message.model_id, "trained").await?;
-    
-    // Update job status to "completed"
-    update_job_status(db, &job_message.job_id, "completed").await?;
-    
-    // Upload job results (could be metrics, logs, etc.)
-    let logs_path = format!("jobs/{}/logs.txt", job_message.job_id);
-    let logs_content = format!("Job {} completed successfully at {}", job_message.job_id, Utc::now().to_rfc3339());
-    
-    // Upload logs to R2
-    bucket.put(&logs_path, logs_content.as_bytes()).execute().await?;
-    
-    // Update job with logs URL
-    update_job_logs_url(db, &job_message.job_id, &logs_path).await?;
-    
+async fn process_job_message(job_message: &JobMessage, db: &D1Database, env: &Env) -> Result<()> {
+    let user_id = get_model_user_id(db, &job_message.model_id).await?;
+
+    // Hand the job off to the runner protocol instead of simulating training
+    // inline: mark it dispatched so a GPU runner can claim it via
+    // POST /runner/claim, then heartbeat and report progress itself.
+    state::transition_job(
+        db,
+        env,
+        &user_id,
+        &job_message.job_id,
+        &job_message.model_id,
+        JobState::Dispatched,
+        None,
+    )
+    .await?;
+
+    console_log!(
+        "Dispatched fine-tuning job {} for model {}, awaiting runner claim",
+        job_message.job_id,
+        job_message.model_id
+    );
+
     Ok(())
 }
 
-async fn update_job_status(db: &D1Database, job_id: &str, status: &str) -> Result<()> {
-    let stmt = format!("UPDATE jobs SET status = ?, updated_at = ? WHERE id = ?");
-    
-    db.prepare(&stmt)
-        .bind(&[
-            status.into(),
-            Utc::now().to_rfc3339().into(),
-            job_id.into(),
-        ])?
-        .run()
-        .await?;
-    
-    Ok(())
+// On failure, either requeue the job for another attempt or, once attempts
+// are exhausted, dead-letter it and persist the last error to R2. Goes
+// through the same attempt-counting/dead-letter logic a runner-reported
+// failure does (see `runner::handle_report`), since both are just different
+// places a job can fail from.
+async fn handle_job_failure(
+    db: &D1Database,
+    bucket: &R2Bucket,
+    env: &Env,
+    job_message: &JobMessage,
+    error: &Error,
+) -> Result<()> {
+    let user_id = get_model_user_id(db, &job_message.model_id).await?;
+
+    state::record_job_failure(
+        db,
+        bucket,
+        env,
+        &user_id,
+        &job_message.job_id,
+        &job_message.model_id,
+        &error.to_string(),
+    )
+    .await
 }
 
-async fn update_model_status(db: &D1Database, model_id: &str, status: &str) -> Result<()> {
-    let stmt = format!("UPDATE models SET status = ?, updated_at = ? WHERE id = ?");
-    
-    db.prepare(&stmt)
-        .bind(&[
-            status.into(),
-            Utc::now().to_rfc3339().into(),
-            model_id.into(),
-        ])?
-        .run()
+async fn get_model_user_id(db: &D1Database, model_id: &str) -> Result<String> {
+    let row = db
+        .prepare("SELECT user_id FROM models WHERE id = ?")
+        .bind(&[model_id.into()])?
+        .first::<ModelUserIdRow>(None)
         .await?;
-    
-    Ok(())
+
+    match row {
+        Some(row) => Ok(row.user_id),
+        None => Err(Error::from(format!("Model {} not found", model_id))),
+    }
 }
 
-async fn update_job_logs_url(db: &D1Database, job_id: &str, logs_url: &str) -> Result<()> {
-    let stmt = format!("UPDATE jobs SET logs_url = ? WHERE id = ?");
-    
-    db.prepare(&stmt)
-        .bind(&[
-            logs_url.into(),
-            job_id.into(),
-        ])?
-        .run()
-        .await?;
-    
-    Ok(())
-} 
\ No newline at end of file
+#[derive(Deserialize)]
+struct ModelUserIdRow {
+    user_id: String,
+}
@@ -1,3 +1,11 @@
+mod auth;
+mod dataset;
+mod job_consumer;
+mod logs;
+mod notifier;
+mod runner;
+mod state;
+
 use serde::{Deserialize, Serialize};
 use worker::*;
 use uuid::Uuid;
@@ -6,7 +14,6 @@ use chrono::Utc;
 // Request data structure
 #[derive(Deserialize)]
 struct FineTuneRequest {
-    user_id: String,
     model_name: String,
     description: Option<String>,
     base_model: String,
@@ -46,11 +53,40 @@ async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
         .post_async("/fine-tune", |mut req, env| async move {
             handle_fine_tune_request(&mut req, &env).await
         })
+        .post_async("/runner/claim", |mut req, env| async move {
+            runner::handle_claim(&mut req, &env).await
+        })
+        .post_async("/runner/:job_id/heartbeat", |mut req, env| async move {
+            runner::handle_heartbeat(&mut req, &env).await
+        })
+        .post_async("/runner/:job_id/report", |mut req, env| async move {
+            runner::handle_report(&mut req, &env).await
+        })
+        .get_async("/jobs/:job_id/logs", |mut req, env| async move {
+            match job_id_from_logs_path(&req) {
+                Ok(job_id) => logs::handle_tail(&mut req, &env, &job_id).await,
+                Err(e) => Response::error(format!("Invalid path: {}", e), 400),
+            }
+        })
         .run(req, env)
         .await
 }
 
 async fn handle_fine_tune_request(req: &mut Request, env: &Env) -> Result<Response> {
+    // Get database binding
+    let db = match env.d1("trainchimp-db") {
+        Ok(db) => db,
+        Err(_) => return Response::error("Database connection error", 500),
+    };
+
+    // Authenticate the request and derive the user_id from the API key's
+    // own row in D1 rather than trusting whatever the client puts in the
+    // body or signs alongside a secret every caller shares.
+    let claims = match auth::verify_signature(req, &db).await {
+        Ok(claims) => claims,
+        Err(e) => return Response::error(format!("Unauthorized: {}", e), 401),
+    };
+
     // Parse request JSON body
     let fine_tune_req = match req.json::<FineTuneRequest>().await {
         Ok(data) => data,
@@ -59,12 +95,6 @@ async fn handle_fine_tune_request(req: &mut Request, env: &Env) -> Result<Respon
         }
     };
 
-    // Get database binding
-    let db = match env.d1("trainchimp-db") {
-        Ok(db) => db,
-        Err(_) => return Response::error("Database connection error", 500),
-    };
-
     // Get R2 binding
     let bucket = match env.r2("my-app-bucket") {
         Ok(bucket) => bucket,
@@ -77,9 +107,25 @@ async fn handle_fine_tune_request(req: &mut Request, env: &Env) -> Result<Respon
         Err(_) => return Response::error("Queue access error", 500),
     };
 
-    // Verify dataset exists and is valid
-    if !verify_dataset(&bucket, &fine_tune_req.dataset_id).await {
-        return Response::error("Dataset not found or invalid", 404);
+    // Validate the dataset's content and shape, not just that it exists.
+    let dataset_report =
+        match dataset::validate_dataset(env, &bucket, &fine_tune_req.dataset_id, &fine_tune_req.base_model).await {
+            Ok(report) => report,
+            Err(e) => return Response::error(e.to_string(), e.status_code()),
+        };
+
+    if fine_tune_req.training_params.batch_size as usize > dataset_report.example_count {
+        return Response::error(
+            format!(
+                "batch_size ({}) cannot exceed the dataset's example count ({})",
+                fine_tune_req.training_params.batch_size, dataset_report.example_count
+            ),
+            422,
+        );
+    }
+
+    if let Err(e) = dataset::persist_report(&db, &dataset_report).await {
+        return Response::error(format!("Failed to persist dataset report: {}", e), 500);
     }
 
     // Generate UUIDs
@@ -96,7 +142,7 @@ async fn handle_fine_tune_request(req: &mut Request, env: &Env) -> Result<Respon
     match db.prepare(&model_stmt)
         .bind(&[
             model_id.clone().into(),
-            fine_tune_req.user_id.into(),
+            claims.user_id.clone().into(),
             fine_tune_req.model_name.into(),
             fine_tune_req.description.unwrap_or_default().into(),
             fine_tune_req.base_model.into(),
@@ -165,19 +211,13 @@ async fn handle_fine_tune_request(req: &mut Request, env: &Env) -> Result<Respon
     Response::from_json(&response)
 }
 
-async fn verify_dataset(bucket: &R2Bucket, dataset_id: &str) -> bool {
-    // Get dataset info from R2
-    let dataset_path = format!("datasets/{}/metadata.json", dataset_id);
-    
-    match bucket.get(&dataset_path).await {
-        Ok(Some(object)) => {
-            // Verify that the dataset exists
-            // For more comprehensive validation, you could:
-            // 1. Check file size
-            // 2. Verify data format
-            // 3. Count examples
-            true
-        },
-        _ => false,
-    }
+// Pulls the `{job_id}` segment out of `/jobs/{job_id}/logs`.
+fn job_id_from_logs_path(req: &Request) -> Result<String> {
+    req.path()
+        .strip_prefix("/jobs/")
+        .and_then(|rest| rest.strip_suffix("/logs"))
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .ok_or_else(|| Error::from("Invalid logs path"))
 }
+
@@ -0,0 +1,196 @@
+// Serves a job's logs/metrics as either a single snapshot or a live tail.
+// Runners append to these R2 objects incrementally via `/runner/{id}/report`
+// (see runner.rs); this module is just the read side.
+
+use chrono::Utc;
+use futures::stream;
+use serde::Deserialize;
+use std::time::Duration;
+use worker::*;
+
+use crate::auth;
+use crate::state::JobState;
+
+const POLL_INTERVAL_MS: u64 = 1500;
+
+pub fn logs_path(job_id: &str) -> String {
+    format!("jobs/{}/logs.txt", job_id)
+}
+
+pub fn metrics_path(job_id: &str) -> String {
+    format!("jobs/{}/metrics.ndjson", job_id)
+}
+
+/// Public URL for a job's `GET /jobs/{id}/logs` endpoint, included in
+/// notifier payloads so a recipient can jump straight to the live log/metric
+/// tail instead of just learning the status changed.
+pub fn public_logs_url(env: &Env, job_id: &str) -> String {
+    let base = env
+        .var("APP_BASE_URL")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| "https://api.trainchimp.dev".to_string());
+    format!("{}/jobs/{}/logs", base, job_id)
+}
+
+// GET /jobs/{job_id}/logs - returns the logs/metrics captured so far. With
+// `?follow=1`, streams new lines as Server-Sent Events until the job reaches
+// a terminal JobState instead of returning once. Requires the same API-key
+// auth as `/fine-tune`, and only the job's owning user may read it.
+pub async fn handle_tail(req: &mut Request, env: &Env, job_id: &str) -> Result<Response> {
+    let follow = req
+        .url()?
+        .query_pairs()
+        .any(|(k, v)| k == "follow" && (v == "1" || v == "true"));
+
+    let db = match env.d1("trainchimp-db") {
+        Ok(db) => db,
+        Err(_) => return Response::error("Database connection error", 500),
+    };
+
+    let claims = match auth::verify_signature(req, &db).await {
+        Ok(claims) => claims,
+        Err(e) => return Response::error(format!("Unauthorized: {}", e), 401),
+    };
+
+    let owner = match job_owner_user_id(&db, job_id).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Response::error("Job not found", 404),
+        Err(e) => return Response::error(format!("Database error: {}", e), 500),
+    };
+
+    if owner != claims.user_id {
+        return Response::error("Forbidden", 403);
+    }
+
+    let bucket = match env.r2("STORAGE") {
+        Ok(bucket) => bucket,
+        Err(_) => return Response::error("Storage access error", 500),
+    };
+
+    if !follow {
+        let logs = read_object(&bucket, &logs_path(job_id)).await?.unwrap_or_default();
+        let metrics = read_object(&bucket, &metrics_path(job_id)).await?.unwrap_or_default();
+        return Response::from_json(&serde_json::json!({ "logs": logs, "metrics": metrics }));
+    }
+
+    let job_id = job_id.to_string();
+    let state = TailState {
+        db,
+        bucket,
+        job_id,
+        log_offset: 0,
+        metrics_offset: 0,
+        done: false,
+    };
+
+    let body_stream = stream::unfold(state, tail_tick);
+
+    let mut response = Response::from_stream(body_stream)?;
+    response
+        .headers_mut()
+        .set("Content-Type", "text/event-stream")?;
+    response.headers_mut().set("Cache-Control", "no-cache")?;
+    Ok(response)
+}
+
+struct TailState {
+    db: D1Database,
+    bucket: R2Bucket,
+    job_id: String,
+    log_offset: usize,
+    metrics_offset: usize,
+    done: bool,
+}
+
+async fn tail_tick(mut state: TailState) -> Option<(Result<Vec<u8>>, TailState)> {
+    if state.done {
+        return None;
+    }
+
+    let mut chunk = String::new();
+
+    if let Ok(Some(logs)) = read_object(&state.bucket, &logs_path(&state.job_id)).await {
+        if logs.len() > state.log_offset {
+            for line in logs[state.log_offset..].lines() {
+                chunk.push_str(&format!("event: log\ndata: {}\n\n", line));
+            }
+            state.log_offset = logs.len();
+        }
+    }
+
+    if let Ok(Some(metrics)) = read_object(&state.bucket, &metrics_path(&state.job_id)).await {
+        if metrics.len() > state.metrics_offset {
+            for line in metrics[state.metrics_offset..].lines() {
+                if !line.is_empty() {
+                    chunk.push_str(&format!("event: metric\ndata: {}\n\n", line));
+                }
+            }
+            state.metrics_offset = metrics.len();
+        }
+    }
+
+    if is_terminal(&state.db, &state.job_id).await {
+        chunk.push_str(&format!(
+            "event: done\ndata: {{\"timestamp\":\"{}\"}}\n\n",
+            Utc::now().to_rfc3339()
+        ));
+        state.done = true;
+    } else {
+        let _ = Delay::from(Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+
+    Some((Ok(chunk.into_bytes()), state))
+}
+
+// Looks up the user_id of the job's owning model, so the caller can check
+// the authenticated user actually owns this job before streaming its logs.
+async fn job_owner_user_id(db: &D1Database, job_id: &str) -> Result<Option<String>> {
+    let row = db
+        .prepare(
+            "SELECT models.user_id AS user_id FROM jobs \
+             JOIN models ON models.id = jobs.model_id \
+             WHERE jobs.id = ?",
+        )
+        .bind(&[job_id.into()])?
+        .first::<OwnerRow>(None)
+        .await?;
+
+    Ok(row.map(|row| row.user_id))
+}
+
+#[derive(Deserialize)]
+struct OwnerRow {
+    user_id: String,
+}
+
+// `Failed` is deliberately excluded: a failed job can still retry back to
+// `Dispatched` (see `state::record_job_failure`), so a client following the
+// stream would see a premature `event: done` on a single failed attempt.
+async fn is_terminal(db: &D1Database, job_id: &str) -> bool {
+    let row = match db.prepare("SELECT status FROM jobs WHERE id = ?").bind(&[job_id.into()]) {
+        Ok(stmt) => stmt.first::<StatusRow>(None).await.unwrap_or(None),
+        Err(_) => None,
+    };
+
+    let status: Option<JobState> = row.and_then(|row| row.status.parse().ok());
+
+    matches!(
+        status,
+        Some(JobState::Completed) | Some(JobState::Cancelled) | Some(JobState::DeadLettered)
+    )
+}
+
+#[derive(Deserialize)]
+struct StatusRow {
+    status: String,
+}
+
+async fn read_object(bucket: &R2Bucket, path: &str) -> Result<Option<String>> {
+    match bucket.get(path).await? {
+        Some(object) => match object.body() {
+            Some(body) => Ok(Some(body.text().await?)),
+            None => Ok(Some(String::new())),
+        },
+        None => Ok(None),
+    }
+}
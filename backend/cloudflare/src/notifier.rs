@@ -0,0 +1,201 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use worker::*;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Mirrors the `notifiers` D1 table: one row per destination a user wants
+// status-change events delivered to.
+#[derive(Debug, Clone)]
+pub struct NotifierConfig {
+    pub id: String,
+    pub user_id: String,
+    pub kind: String, // "webhook" or "email"
+    pub target: String,
+    pub secret: String,
+}
+
+// Payload shipped to every notifier when a job or model changes status.
+#[derive(Serialize)]
+pub struct StatusChangePayload<'a> {
+    pub job_id: &'a str,
+    pub model_id: &'a str,
+    pub old_status: &'a str,
+    pub new_status: &'a str,
+    pub timestamp: String,
+    pub logs_url: Option<&'a str>,
+}
+
+// Looks up every notifier registered for `user_id` and dispatches the status
+// change to each one. Best-effort: a failing notifier is logged and skipped,
+// never surfaced to the caller, since a broken webhook must not fail the job.
+pub async fn notify_status_change(
+    db: &D1Database,
+    env: &Env,
+    user_id: &str,
+    job_id: &str,
+    model_id: &str,
+    old_status: &str,
+    new_status: &str,
+    logs_url: Option<&str>,
+) {
+    let notifiers = match list_notifiers(db, user_id).await {
+        Ok(notifiers) => notifiers,
+        Err(e) => {
+            console_error!("Failed to load notifiers for user {}: {}", user_id, e);
+            return;
+        }
+    };
+
+    if notifiers.is_empty() {
+        return;
+    }
+
+    let payload = StatusChangePayload {
+        job_id,
+        model_id,
+        old_status,
+        new_status,
+        timestamp: Utc::now().to_rfc3339(),
+        logs_url,
+    };
+
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            console_error!("Failed to serialize notification payload: {}", e);
+            return;
+        }
+    };
+
+    for notifier in notifiers {
+        let result = match notifier.kind.as_str() {
+            "webhook" => dispatch_webhook(&notifier, &body).await,
+            "email" => dispatch_email(env, &notifier, &body).await,
+            other => Err(Error::from(format!("Unknown notifier kind: {}", other))),
+        };
+
+        if let Err(e) = result {
+            console_error!(
+                "Notifier {} ({}) failed for job {}: {}",
+                notifier.id,
+                notifier.kind,
+                job_id,
+                e
+            );
+        }
+    }
+}
+
+async fn list_notifiers(db: &D1Database, user_id: &str) -> Result<Vec<NotifierConfig>> {
+    let stmt = "SELECT id, user_id, kind, target, secret FROM notifiers WHERE user_id = ?";
+
+    let rows = db
+        .prepare(stmt)
+        .bind(&[user_id.into()])?
+        .all()
+        .await?
+        .results::<NotifierRow>()?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| NotifierConfig {
+            id: row.id,
+            user_id: row.user_id,
+            kind: row.kind,
+            target: row.target,
+            secret: row.secret,
+        })
+        .collect())
+}
+
+#[derive(serde::Deserialize)]
+struct NotifierRow {
+    id: String,
+    user_id: String,
+    kind: String,
+    target: String,
+    secret: String,
+}
+
+// Signs the raw JSON body with the notifier's secret and POSTs it to the
+// configured webhook target, carrying the signature in `X-Trainchimp-Signature`.
+async fn dispatch_webhook(notifier: &NotifierConfig, body: &str) -> Result<()> {
+    let signature = sign_payload(&notifier.secret, body)?;
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+    headers.set("X-Trainchimp-Signature", &signature)?;
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(body.into()));
+
+    let request = Request::new_with_init(&notifier.target, &init)?;
+    let mut response = Fetch::Request(request).send().await?;
+
+    if response.status_code() >= 400 {
+        return Err(Error::from(format!(
+            "Webhook notifier {} returned status {}: {}",
+            notifier.id,
+            response.status_code(),
+            response.text().await.unwrap_or_default()
+        )));
+    }
+
+    Ok(())
+}
+
+// Sends a templated summary email via an outbound email binding (e.g.
+// MailChannels) reachable from the Worker over fetch.
+async fn dispatch_email(env: &Env, notifier: &NotifierConfig, body: &str) -> Result<()> {
+    let summary = format!(
+        "TrainChimp job update\n\n{}",
+        body
+    );
+
+    let message = serde_json::json!({
+        "personalizations": [{ "to": [{ "email": notifier.target }] }],
+        "from": { "email": "notifications@trainchimp.dev", "name": "TrainChimp" },
+        "subject": "TrainChimp job status update",
+        "content": [{ "type": "text/plain", "value": summary }],
+    });
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers({
+            let mut headers = Headers::new();
+            headers.set("Content-Type", "application/json")?;
+            headers
+        })
+        .with_body(Some(message.to_string().into()));
+
+    let endpoint = env
+        .var("MAILCHANNELS_ENDPOINT")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| "https://api.mailchannels.net/tx/v1/send".to_string());
+
+    let request = Request::new_with_init(&endpoint, &init)?;
+    let mut response = Fetch::Request(request).send().await?;
+
+    if response.status_code() >= 400 {
+        return Err(Error::from(format!(
+            "Email notifier {} returned status {}: {}",
+            notifier.id,
+            response.status_code(),
+            response.text().await.unwrap_or_default()
+        )));
+    }
+
+    Ok(())
+}
+
+fn sign_payload(secret: &str, body: &str) -> Result<String> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| Error::from(format!("Invalid notifier secret: {}", e)))?;
+    mac.update(body.as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
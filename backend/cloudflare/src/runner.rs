@@ -0,0 +1,458 @@
+// Driver/runner protocol: external GPU workers claim dispatched jobs, send
+// heartbeats while they train, and report progress/completion back. This
+// replaces the old in-Worker simulated training in `job_consumer`.
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+use crate::auth;
+use crate::logs::{logs_path, metrics_path, public_logs_url};
+use crate::notifier;
+use crate::state::{self, JobState, ModelState};
+
+/// How long a claimed job may go without a heartbeat before it's considered
+/// abandoned and re-queued by the lease-expiry sweep.
+const LEASE_DURATION_SECS: i64 = 120;
+
+pub mod proto {
+    use serde::{Deserialize, Serialize};
+
+    /// Everything a runner needs to start executing a dispatched job.
+    /// Shared by the claim response so the driver (Worker) and runner agree
+    /// on one schema.
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct TaskInfo {
+        pub job_id: String,
+        pub model_id: String,
+        pub dataset_id: String,
+        pub base_model: String,
+        pub training_params: serde_json::Value,
+        pub dataset_url: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RunnerCapabilities {
+        pub gpu_type: String,
+        pub vram_gb: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ClaimRequest {
+        pub capabilities: RunnerCapabilities,
+    }
+
+    #[derive(Serialize)]
+    pub struct ClaimResponse {
+        pub task: Option<TaskInfo>,
+    }
+
+    #[derive(Serialize)]
+    pub struct HeartbeatResponse {
+        pub lease_expires_at: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ReportRequest {
+        pub state: String,
+        pub step: Option<u32>,
+        pub metrics: Option<serde_json::Value>,
+        pub log_chunk: Option<String>,
+        pub error: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    pub struct ReportResponse {
+        pub acknowledged: bool,
+    }
+}
+
+use proto::*;
+
+// POST /runner/claim - a runner presents its capabilities and atomically
+// claims the oldest dispatched job via a conditional UPDATE.
+pub async fn handle_claim(req: &mut Request, env: &Env) -> Result<Response> {
+    let secret = match auth::runner_secret(env) {
+        Ok(secret) => secret,
+        Err(_) => return Response::error("Auth configuration error", 500),
+    };
+    if let Err(e) = auth::verify_runner_signature(req, &secret).await {
+        return Response::error(format!("Unauthorized: {}", e), 401);
+    }
+
+    let claim_req = match req.json::<ClaimRequest>().await {
+        Ok(data) => data,
+        Err(e) => return Response::error(format!("Invalid request: {}", e), 400),
+    };
+
+    let db = match env.d1("trainchimp-db") {
+        Ok(db) => db,
+        Err(_) => return Response::error("Database connection error", 500),
+    };
+
+    let bucket = match env.r2("my-app-bucket") {
+        Ok(bucket) => bucket,
+        Err(_) => return Response::error("Storage access error", 500),
+    };
+
+    let lease_expires_at = (Utc::now() + Duration::seconds(LEASE_DURATION_SECS)).to_rfc3339();
+
+    // Claim the oldest dispatched job in one conditional statement so two
+    // runners racing the same poll can't both win it. Moving straight to
+    // "processing" keeps the job in a single claimed state rather than
+    // introducing a separate "claimed" status.
+    let claimed = db
+        .prepare(
+            "UPDATE jobs SET status = 'processing', lease_expires_at = ?, claimed_gpu_type = ?, claimed_vram_gb = ? \
+             WHERE id = (SELECT id FROM jobs WHERE status = 'dispatched' ORDER BY created_at ASC LIMIT 1) \
+             RETURNING id, model_id, dataset_id",
+        )
+        .bind(&[
+            lease_expires_at.clone().into(),
+            claim_req.capabilities.gpu_type.into(),
+            claim_req.capabilities.vram_gb.into(),
+        ])?
+        .first::<ClaimedJobRow>(None)
+        .await?;
+
+    let claimed = match claimed {
+        Some(row) => row,
+        None => return Response::from_json(&ClaimResponse { task: None }),
+    };
+
+    let user_id = get_model_user_id(&db, &claimed.model_id).await?;
+    let logs_url = public_logs_url(env, &claimed.id);
+    notifier::notify_status_change(
+        &db,
+        env,
+        &user_id,
+        &claimed.id,
+        &claimed.model_id,
+        &JobState::Dispatched.to_string(),
+        &JobState::Processing.to_string(),
+        Some(&logs_url),
+    )
+    .await;
+
+    // First claim is also the model's first transition out of "pending" -
+    // without this, the first report::completed/failed call has no legal
+    // edge to land on from the model's still-pending state. Only do this
+    // once: a later re-claim of the same model's job (e.g. after the lease
+    // sweep re-dispatches a job whose runner crashed) finds the model
+    // already "training", and `ModelState::can_transition_to` has no
+    // `Training -> Training` self-edge.
+    let model_status = db
+        .prepare("SELECT status FROM models WHERE id = ?")
+        .bind(&[claimed.model_id.clone().into()])?
+        .first::<ModelStatusRow>(None)
+        .await?
+        .map(|row| row.status);
+
+    if model_status.as_deref() == Some(ModelState::Pending.to_string().as_str()) {
+        state::transition_model(
+            &db,
+            env,
+            &user_id,
+            &claimed.id,
+            &claimed.model_id,
+            ModelState::Training,
+        )
+        .await?;
+    }
+
+    let model = db
+        .prepare("SELECT base_model FROM models WHERE id = ?")
+        .bind(&[claimed.model_id.clone().into()])?
+        .first::<BaseModelRow>(None)
+        .await?;
+
+    let base_model = match model {
+        Some(row) => row.base_model,
+        None => return Response::error("Model not found for claimed job", 500),
+    };
+
+    let params = db
+        .prepare("SELECT training_params FROM jobs WHERE id = ?")
+        .bind(&[claimed.id.clone().into()])?
+        .first::<TrainingParamsRow>(None)
+        .await?
+        .map(|row| row.training_params)
+        .unwrap_or(serde_json::Value::Null);
+
+    let dataset_url = presign_dataset_url(env, &claimed.dataset_id)?;
+
+    let task = TaskInfo {
+        job_id: claimed.id,
+        model_id: claimed.model_id,
+        dataset_id: claimed.dataset_id,
+        base_model,
+        training_params: params,
+        dataset_url,
+    };
+
+    let _ = bucket; // dataset presence already validated at submission time
+
+    Response::from_json(&ClaimResponse { task: Some(task) })
+}
+
+#[derive(Deserialize)]
+struct ClaimedJobRow {
+    id: String,
+    model_id: String,
+    dataset_id: String,
+}
+
+#[derive(Deserialize)]
+struct BaseModelRow {
+    base_model: String,
+}
+
+#[derive(Deserialize)]
+struct ModelStatusRow {
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct TrainingParamsRow {
+    training_params: serde_json::Value,
+}
+
+// POST /runner/{job_id}/heartbeat - extends the lease on a claimed job.
+pub async fn handle_heartbeat(req: &mut Request, env: &Env) -> Result<Response> {
+    let secret = match auth::runner_secret(env) {
+        Ok(secret) => secret,
+        Err(_) => return Response::error("Auth configuration error", 500),
+    };
+    if let Err(e) = auth::verify_runner_signature(req, &secret).await {
+        return Response::error(format!("Unauthorized: {}", e), 401);
+    }
+
+    let job_id = job_id_from_path(req, "/heartbeat")?;
+    let job_id = job_id.as_str();
+
+    let db = match env.d1("trainchimp-db") {
+        Ok(db) => db,
+        Err(_) => return Response::error("Database connection error", 500),
+    };
+
+    let lease_expires_at = (Utc::now() + Duration::seconds(LEASE_DURATION_SECS)).to_rfc3339();
+
+    db.prepare("UPDATE jobs SET lease_expires_at = ? WHERE id = ? AND status NOT IN ('completed', 'failed', 'cancelled', 'dead_lettered')")
+        .bind(&[lease_expires_at.clone().into(), job_id.into()])?
+        .run()
+        .await?;
+
+    Response::from_json(&HeartbeatResponse { lease_expires_at })
+}
+
+// POST /runner/{job_id}/report - runner-reported progress or terminal state.
+pub async fn handle_report(req: &mut Request, env: &Env) -> Result<Response> {
+    let secret = match auth::runner_secret(env) {
+        Ok(secret) => secret,
+        Err(_) => return Response::error("Auth configuration error", 500),
+    };
+    if let Err(e) = auth::verify_runner_signature(req, &secret).await {
+        return Response::error(format!("Unauthorized: {}", e), 401);
+    }
+
+    let job_id = job_id_from_path(req, "/report")?;
+    let job_id = job_id.as_str();
+
+    let report = match req.json::<ReportRequest>().await {
+        Ok(data) => data,
+        Err(e) => return Response::error(format!("Invalid request: {}", e), 400),
+    };
+
+    let db = match env.d1("trainchimp-db") {
+        Ok(db) => db,
+        Err(_) => return Response::error("Database connection error", 500),
+    };
+
+    let bucket = match env.r2("STORAGE") {
+        Ok(bucket) => bucket,
+        Err(_) => return Response::error("Storage access error", 500),
+    };
+
+    if let Some(chunk) = &report.log_chunk {
+        let timestamp = Utc::now().to_rfc3339();
+        let stamped = chunk
+            .lines()
+            .map(|line| format!("[{}] {}", timestamp, line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Err(e) = append_line(&bucket, &logs_path(job_id), &stamped).await {
+            console_error!("Failed to append log chunk for job {}: {}", job_id, e);
+        }
+    }
+
+    if let Some(metrics) = &report.metrics {
+        let line = serde_json::json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "step": report.step,
+            "metrics": metrics,
+        });
+        if let Err(e) = append_line(&bucket, &metrics_path(job_id), &line.to_string()).await {
+            console_error!("Failed to append metrics for job {}: {}", job_id, e);
+        }
+    }
+
+    let job = db
+        .prepare("SELECT model_id FROM jobs WHERE id = ?")
+        .bind(&[job_id.into()])?
+        .first::<JobLookupRow>(None)
+        .await?;
+
+    let job = match job {
+        Some(job) => job,
+        None => return Response::error("Job not found", 404),
+    };
+
+    let user_id = get_model_user_id(&db, &job.model_id).await?;
+
+    let next_state: JobState = match report.state.parse() {
+        Ok(state) => state,
+        Err(e) => return Response::error(format!("Invalid state: {}", e), 400),
+    };
+
+    // A reported failure goes through the same attempt-counting/dead-letter
+    // logic a dispatch failure does (see `job_consumer::handle_job_failure`)
+    // instead of dropping the job straight to `Failed` with no retry.
+    match next_state {
+        JobState::Failed => {
+            state::record_job_failure(
+                &db,
+                &bucket,
+                env,
+                &user_id,
+                job_id,
+                &job.model_id,
+                report.error.as_deref().unwrap_or("runner reported failure"),
+            )
+            .await?;
+        }
+        JobState::Completed => {
+            state::transition_job(
+                &db,
+                env,
+                &user_id,
+                job_id,
+                &job.model_id,
+                next_state,
+                report.error.as_deref(),
+            )
+            .await?;
+            state::transition_model(&db, env, &user_id, job_id, &job.model_id, ModelState::Trained).await?;
+        }
+        // A progress report: the claim already moved the job to Processing,
+        // so only the log chunk and metrics appended above are recorded.
+        JobState::Processing => {}
+        _ => {
+            state::transition_job(
+                &db,
+                env,
+                &user_id,
+                job_id,
+                &job.model_id,
+                next_state,
+                report.error.as_deref(),
+            )
+            .await?;
+        }
+    }
+
+    Response::from_json(&ReportResponse { acknowledged: true })
+}
+
+#[derive(Deserialize)]
+struct JobLookupRow {
+    model_id: String,
+}
+
+async fn get_model_user_id(db: &D1Database, model_id: &str) -> Result<String> {
+    let row = db
+        .prepare("SELECT user_id FROM models WHERE id = ?")
+        .bind(&[model_id.into()])?
+        .first::<ModelUserIdRow>(None)
+        .await?;
+
+    match row {
+        Some(row) => Ok(row.user_id),
+        None => Err(Error::from(format!("Model {} not found", model_id))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelUserIdRow {
+    user_id: String,
+}
+
+// Re-queues jobs whose lease has expired because their runner crashed or
+// stopped heartbeating, so they aren't stranded in "processing" forever.
+// Goes straight back to "dispatched" (not "queued") since `/runner/claim`
+// only ever selects dispatched jobs, and nothing re-sends a queue message
+// to drive a "queued" job back through `process_job_message`. Uses a
+// direct UPDATE rather than `state::transition_job` because it's a bulk
+// sweep with no single old/new status pair to validate against.
+pub async fn sweep_expired_leases(db: &D1Database) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+
+    db.prepare(&format!(
+        "UPDATE jobs SET status = '{dispatched}', lease_expires_at = NULL \
+         WHERE status = '{processing}' AND lease_expires_at < ?",
+        dispatched = JobState::Dispatched,
+        processing = JobState::Processing,
+    ))
+    .bind(&[now.into()])?
+    .run()
+    .await?;
+
+    Ok(())
+}
+
+// R2 has no native append, so incremental log/metric writes are a
+// read-modify-write: fetch whatever's there, tack on the line (callers
+// timestamp it first), put it back.
+async fn append_line(bucket: &R2Bucket, path: &str, line: &str) -> Result<()> {
+    let existing = match bucket.get(path).await? {
+        Some(object) => match object.body() {
+            Some(body) => body.text().await?,
+            None => String::new(),
+        },
+        None => String::new(),
+    };
+
+    let mut content = existing;
+    content.push_str(line.trim_end());
+    content.push('\n');
+
+    bucket.put(path, content.into_bytes()).execute().await?;
+
+    Ok(())
+}
+
+// Pulls the `{job_id}` segment out of `/runner/{job_id}/{suffix}`, since the
+// handlers are given only the request and not the router's matched params.
+fn job_id_from_path(req: &Request, suffix: &str) -> Result<String> {
+    req.path()
+        .strip_prefix("/runner/")
+        .and_then(|rest| rest.strip_suffix(suffix))
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .ok_or_else(|| Error::from("Invalid runner path"))
+}
+
+// Dataset URL handed to a runner: the R2 public base URL for the dataset
+// object plus an `expires` query parameter one hour out. The URL carries no
+// signature, and nothing here enforces the expiry.
+fn presign_dataset_url(env: &Env, dataset_id: &str) -> Result<String> {
+    let expires_at = (Utc::now() + Duration::seconds(3600)).timestamp();
+    let base = env
+        .var("R2_PUBLIC_BASE_URL")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| "https://assets.trainchimp.dev".to_string());
+
+    Ok(format!(
+        "{}/datasets/{}/data.jsonl?expires={}",
+        base, dataset_id, expires_at
+    ))
+}
@@ -0,0 +1,325 @@
+// Typed job/model status machine. Replaces the old stringly-typed statuses
+// (`update_job_status(db, id, "processing")`) so illegal transitions like
+// "processing" -> "queued", or skipping straight to "completed", are
+// rejected rather than silently corrupting the row.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Utc;
+use serde::Deserialize;
+use worker::*;
+
+use crate::logs;
+use crate::notifier;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Dispatched,
+    Processing,
+    Completed,
+    Failed,
+    Cancelled,
+    DeadLettered,
+}
+
+impl JobState {
+    /// Legal edges in the job lifecycle. A runner claims a dispatched job
+    /// (-> Processing), a queued job that fails to dispatch goes to Failed,
+    /// and a failed job is either requeued for another attempt
+    /// or dead-lettered once retries are exhausted. Both the lease sweep and
+    /// a retriable failure go straight back to Dispatched rather than
+    /// Queued, since only `/runner/claim` can move a job forward again and
+    /// it only ever looks at dispatched jobs.
+    pub fn can_transition_to(&self, next: JobState) -> bool {
+        use JobState::*;
+        matches!(
+            (self, next),
+            (Queued, Dispatched)
+                | (Queued, Cancelled)
+                | (Queued, Failed)
+                | (Dispatched, Processing)
+                | (Dispatched, Cancelled)
+                | (Processing, Completed)
+                | (Processing, Failed)
+                | (Processing, Cancelled)
+                | (Processing, Dispatched)
+                | (Failed, Dispatched)
+                | (Failed, DeadLettered)
+        )
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            JobState::Queued => "queued",
+            JobState::Dispatched => "dispatched",
+            JobState::Processing => "processing",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+            JobState::DeadLettered => "dead_lettered",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for JobState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "queued" => Ok(JobState::Queued),
+            "dispatched" => Ok(JobState::Dispatched),
+            "processing" => Ok(JobState::Processing),
+            "completed" => Ok(JobState::Completed),
+            "failed" => Ok(JobState::Failed),
+            "cancelled" => Ok(JobState::Cancelled),
+            "dead_lettered" => Ok(JobState::DeadLettered),
+            other => Err(Error::from(format!("Unknown job state: {}", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelState {
+    Pending,
+    Training,
+    Trained,
+    Failed,
+    Cancelled,
+}
+
+impl ModelState {
+    pub fn can_transition_to(&self, next: ModelState) -> bool {
+        use ModelState::*;
+        matches!(
+            (self, next),
+            (Pending, Training)
+                | (Pending, Cancelled)
+                | (Training, Trained)
+                | (Training, Failed)
+                | (Training, Cancelled)
+        )
+    }
+}
+
+impl fmt::Display for ModelState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ModelState::Pending => "pending",
+            ModelState::Training => "training",
+            ModelState::Trained => "trained",
+            ModelState::Failed => "failed",
+            ModelState::Cancelled => "cancelled",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for ModelState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(ModelState::Pending),
+            "training" => Ok(ModelState::Training),
+            "trained" => Ok(ModelState::Trained),
+            "failed" => Ok(ModelState::Failed),
+            "cancelled" => Ok(ModelState::Cancelled),
+            other => Err(Error::from(format!("Unknown model state: {}", other))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StatusRow {
+    status: String,
+}
+
+/// Moves a job to `next`, rejecting the update (and logging it) if the
+/// transition isn't legal for the job's current state. On success, fires
+/// the notifier subsystem with the old/new status pair.
+pub async fn transition_job(
+    db: &D1Database,
+    env: &Env,
+    user_id: &str,
+    job_id: &str,
+    model_id: &str,
+    next: JobState,
+    last_error: Option<&str>,
+) -> Result<()> {
+    let current = db
+        .prepare("SELECT status FROM jobs WHERE id = ?")
+        .bind(&[job_id.into()])?
+        .first::<StatusRow>(None)
+        .await?
+        .ok_or_else(|| Error::from(format!("Job {} not found", job_id)))?
+        .status;
+
+    let old_state: JobState = current.parse()?;
+
+    if !old_state.can_transition_to(next) {
+        let message = format!(
+            "Illegal job transition {} -> {} for job {}",
+            old_state, next, job_id
+        );
+        console_error!("{}", message);
+        return Err(Error::from(message));
+    }
+
+    if let Some(error) = last_error {
+        db.prepare("UPDATE jobs SET status = ?, updated_at = ?, last_error = ? WHERE id = ?")
+            .bind(&[
+                next.to_string().into(),
+                Utc::now().to_rfc3339().into(),
+                error.into(),
+                job_id.into(),
+            ])?
+            .run()
+            .await?;
+    } else {
+        db.prepare("UPDATE jobs SET status = ?, updated_at = ? WHERE id = ?")
+            .bind(&[
+                next.to_string().into(),
+                Utc::now().to_rfc3339().into(),
+                job_id.into(),
+            ])?
+            .run()
+            .await?;
+    }
+
+    let logs_url = logs::public_logs_url(env, job_id);
+    notifier::notify_status_change(
+        db,
+        env,
+        user_id,
+        job_id,
+        model_id,
+        &old_state.to_string(),
+        &next.to_string(),
+        Some(&logs_url),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Same as `transition_job` but for the `models` table.
+pub async fn transition_model(
+    db: &D1Database,
+    env: &Env,
+    user_id: &str,
+    job_id: &str,
+    model_id: &str,
+    next: ModelState,
+) -> Result<()> {
+    let current = db
+        .prepare("SELECT status FROM models WHERE id = ?")
+        .bind(&[model_id.into()])?
+        .first::<StatusRow>(None)
+        .await?
+        .ok_or_else(|| Error::from(format!("Model {} not found", model_id)))?
+        .status;
+
+    let old_state: ModelState = current.parse()?;
+
+    if !old_state.can_transition_to(next) {
+        let message = format!(
+            "Illegal model transition {} -> {} for model {}",
+            old_state, next, model_id
+        );
+        console_error!("{}", message);
+        return Err(Error::from(message));
+    }
+
+    db.prepare("UPDATE models SET status = ?, updated_at = ? WHERE id = ?")
+        .bind(&[
+            next.to_string().into(),
+            Utc::now().to_rfc3339().into(),
+            model_id.into(),
+        ])?
+        .run()
+        .await?;
+
+    let logs_url = logs::public_logs_url(env, job_id);
+    notifier::notify_status_change(
+        db,
+        env,
+        user_id,
+        job_id,
+        model_id,
+        &old_state.to_string(),
+        &next.to_string(),
+        Some(&logs_url),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Jobs that keep failing are dead-lettered instead of retried forever.
+/// Shared by every path that can observe a job failure (queue dispatch
+/// errors, a runner reporting a failed run) so none of them can bypass the
+/// retry cap.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Records a job failure, wherever it was observed from, and either puts the
+/// job back to `Dispatched` for another attempt or dead-letters it once
+/// `MAX_ATTEMPTS` is exhausted. The model is only moved to `Failed` on
+/// dead-letter; a retriable failure leaves the model's status unchanged since
+/// it may still complete.
+pub async fn record_job_failure(
+    db: &D1Database,
+    bucket: &R2Bucket,
+    env: &Env,
+    user_id: &str,
+    job_id: &str,
+    model_id: &str,
+    error_text: &str,
+) -> Result<()> {
+    transition_job(
+        db,
+        env,
+        user_id,
+        job_id,
+        model_id,
+        JobState::Failed,
+        Some(error_text),
+    )
+    .await?;
+
+    let attempts = increment_attempts(db, job_id).await?;
+
+    if attempts < MAX_ATTEMPTS {
+        transition_job(db, env, user_id, job_id, model_id, JobState::Dispatched, None).await?;
+    } else {
+        let error_path = format!("jobs/{}/error.txt", job_id);
+        bucket.put(&error_path, error_text.to_string().into_bytes()).execute().await?;
+
+        transition_job(db, env, user_id, job_id, model_id, JobState::DeadLettered, None).await?;
+        transition_model(db, env, user_id, job_id, model_id, ModelState::Failed).await?;
+    }
+
+    Ok(())
+}
+
+/// Atomically bumps the job's attempt counter and returns the new count, so
+/// callers can decide whether to retry or dead-letter.
+pub async fn increment_attempts(db: &D1Database, job_id: &str) -> Result<u32> {
+    let row = db
+        .prepare("UPDATE jobs SET attempts = attempts + 1 WHERE id = ? RETURNING attempts")
+        .bind(&[job_id.into()])?
+        .first::<AttemptsRow>(None)
+        .await?
+        .ok_or_else(|| Error::from(format!("Job {} not found", job_id)))?;
+
+    Ok(row.attempts)
+}
+
+#[derive(Deserialize)]
+struct AttemptsRow {
+    attempts: u32,
+}